@@ -1,6 +1,9 @@
 use itertools::Itertools;
+use rand::{Rng, SeedableRng, StdRng};
 use rusty_machine::linalg::{Matrix, BaseMatrix};
 use rusty_machine::learning::LearningResult;
+#[cfg(feature = "serde")]
+use serde::{Serialize, Deserialize};
 
 use ncm::NonConformityScorer;
 
@@ -11,10 +14,51 @@ pub trait ConfidencePredictor<T> {
     fn predict(&mut self, inputs: &Vec<T>) -> LearningResult<Matrix<bool>>;
     fn predict_confidence(&mut self, inputs: &Vec<T>) -> LearningResult<Matrix<f64>>;
     fn set_epsilon(&mut self, epsilon: f64);
-    // TODO:
-    // fn predict_region(&self, pvalues: &Matrix<f64>, epsilon: f64) -> ...
-    // fn update(&self, inputs: &Vec<T>, targets: &Vec<usize>) -> LearningResult<()>;
 
+    /// Converts a p-value matrix (as returned by `predict_confidence`) into,
+    /// for each test object, the set of labels whose p-value exceeds
+    /// `epsilon` together with the forced point prediction (the label with
+    /// the largest p-value) and its confidence and credibility.
+    fn predict_region(&self, pvalues: &Matrix<f64>, epsilon: f64) -> Vec<RegionPrediction> {
+        (0..pvalues.rows()).map(|i| {
+            let mut label_pvalues = (0..pvalues.cols())
+                .map(|j| pvalues[[i,j]])
+                .collect::<Vec<_>>();
+            label_pvalues.sort_by(|a, b| b.partial_cmp(a).unwrap());
+
+            let labels = (0..pvalues.cols())
+                .filter(|&j| pvalues[[i,j]] > epsilon)
+                .collect();
+
+            let point = (0..pvalues.cols())
+                .max_by(|&a, &b| pvalues[[i,a]].partial_cmp(&pvalues[[i,b]]).unwrap())
+                .expect("pvalues must have at least one label column");
+
+            RegionPrediction {
+                labels: labels,
+                point: point,
+                credibility: label_pvalues[0],
+                confidence: 1.0 - label_pvalues.get(1).cloned().unwrap_or(0.0),
+            }
+        }).collect()
+    }
+
+}
+
+/// A region prediction for a single test object, derived from a row of
+/// the p-value matrix: the set of labels whose p-value exceeds `epsilon`,
+/// plus the forced single-label prediction (the label with the largest
+/// p-value) and its confidence and credibility.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RegionPrediction {
+    /// Labels whose p-value exceeds `epsilon`.
+    pub labels: Vec<usize>,
+    /// The label with the largest p-value.
+    pub point: usize,
+    /// 1 minus the second-largest p-value.
+    pub confidence: f64,
+    /// The largest p-value.
+    pub credibility: f64,
 }
 
 /// Transductive Conformal Predictor
@@ -24,11 +68,40 @@ pub struct CP<T> {
     ncm: Box<NonConformityScorer<T>>,
     epsilon: Option<f64>,
     smooth: bool,
+    /* RNG used to draw the smoothing tie-breaker tau ~ Uniform(0,1), one
+     * fresh draw per (test object, label) pair. Boxed so that it can be
+     * either a seeded StdRng (reproducible runs) or one seeded from entropy.
+     */
+    rng: Box<Rng>,
     /* Training inputs are stored in a train_inputs, indexed
      * by a label y, where train_inputs[y] contains all training
      * inputs with label y.
      */
     train_inputs: Option<Vec<Vec<T>>>,
+    /* Running counts backing error_rate(): how many examples passed
+     * through update() were checked against a prediction (those absorbed
+     * while there was no training set yet are not counted) and how many
+     * of those had a true label that fell outside the region predicted
+     * (at epsilon) just before being absorbed into the training set.
+     */
+    n_examples: usize,
+    n_errors: usize,
+}
+
+/// The persistable part of a trained `CP`: everything except the
+/// `NonConformityScorer` and the RNG, neither of which are data (the former
+/// is behavior supplied by the caller, the latter is ephemeral). Restored
+/// via `CP::from_state`, which takes the `ncm` back from the caller.
+///
+/// Requires the `serde` feature.
+#[cfg(feature = "serde")]
+#[derive(Serialize, Deserialize)]
+pub struct CPState<T> {
+    epsilon: Option<f64>,
+    smooth: bool,
+    train_inputs: Option<Vec<Vec<T>>>,
+    n_examples: usize,
+    n_errors: usize,
 }
 
 impl<T> CP<T> {
@@ -38,7 +111,50 @@ impl<T> CP<T> {
             ncm: ncm,
             epsilon: epsilon,
             smooth: smooth,
+            rng: Box::new(::rand::thread_rng()),
             train_inputs: None,
+            n_examples: 0,
+            n_errors: 0,
+        }
+    }
+
+    /// Seed the RNG used to draw the smoothing tie-breaker `tau` so that
+    /// smoothed p-values (see `predict_confidence`) are reproducible across
+    /// runs. Has no effect unless `smooth` is true.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = Box::new(StdRng::from_seed(&[seed as usize]));
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<T> CP<T> where T: Clone {
+    /// Extracts the persistable state of this `CP` (epsilon, smooth, and
+    /// the bucketed training inputs) so it can be written to
+    /// JSON/bincode/etc. and restored later with `from_state`, avoiding the
+    /// need to re-ingest and re-bucket the training set on every process
+    /// start.
+    pub fn to_state(&self) -> CPState<T> {
+        CPState {
+            epsilon: self.epsilon,
+            smooth: self.smooth,
+            train_inputs: self.train_inputs.clone(),
+            n_examples: self.n_examples,
+            n_errors: self.n_errors,
+        }
+    }
+
+    /// Restores a `CP` from a `CPState` previously produced by `to_state`.
+    /// The `NonConformityScorer` isn't part of the saved state (it's
+    /// behavior, not data) and must be supplied again by the caller.
+    pub fn from_state(ncm: Box<NonConformityScorer<T>>, state: CPState<T>) -> CP<T> {
+        CP {
+            ncm: ncm,
+            epsilon: state.epsilon,
+            smooth: state.smooth,
+            rng: Box::new(::rand::thread_rng()),
+            train_inputs: state.train_inputs,
+            n_examples: state.n_examples,
+            n_errors: state.n_errors,
         }
     }
 }
@@ -87,7 +203,14 @@ impl<T> ConfidencePredictor<T> for CP<T> where T: Clone {
     }
 
     /// Returns the p-values corresponding to the labels
-    /// for each object provided as input.
+    /// for each object provided as input. `CP` is inherently Mondrian /
+    /// label-conditional: the p-value for hypothesized label `y` is always
+    /// computed using only the nonconformity scores of examples that
+    /// already carry label `y` (never pooled across other labels' buckets,
+    /// which would not be exchangeable and so wouldn't be a valid p-value),
+    /// so the error rate is controlled *within each class* at `epsilon`
+    /// rather than only on average across classes. There is no marginal
+    /// mode to opt out of this.
     fn predict_confidence(&mut self, inputs: &Vec<T>) -> LearningResult<Matrix<f64>> {
 
         let error_msg = "You should train the model first";
@@ -127,27 +250,27 @@ impl<T> ConfidencePredictor<T> for CP<T> where T: Clone {
                     let train_inputs = self.train_inputs.as_ref()
                                                         .expect(error_msg)[y]
                                                         .as_slice();
-                    (0..n_tmp).into_iter()
-                              .map(|j| self.ncm.score(j, train_inputs))
+                    (0..n_tmp).map(|j| self.ncm.score(j, train_inputs))
                               .collect::<Vec<_>>()
                 };
 
                 /* Compute p-value for the current label.
                  */
+                let alpha_test = scores[n_tmp-1];
+
                 let pvalue = if self.smooth {
-                    unimplemented!();
-
-                    let r = 0.1;
-                    let a = scores.iter()
-                                  .filter(|&s| *s > scores[n_tmp-1])
-                                  .count() as f64;
-                    let b = scores.iter()
-                                  .filter(|&s| *s == scores[n_tmp-1])
-                                  .count() as f64;
-                    (a + r*b) / n_tmp as f64
+                    /* tau ~ Uniform(0,1), drawn fresh for each (test object,
+                     * label) pair, breaks ties among nonconformity scores so
+                     * that the resulting p-value is exactly (not just
+                     * conservatively) uniform under exchangeability.
+                     */
+                    let tau: f64 = self.rng.gen();
+                    let a = scores.iter().filter(|&s| *s > alpha_test).count();
+                    let b = scores.iter().filter(|&s| *s == alpha_test).count();
+                    (a as f64 + tau*(b as f64)) / n_tmp as f64
                 } else {
                     scores.iter()
-                          .filter(|&s| *s >= scores[n_tmp-1])
+                          .filter(|&s| *s >= alpha_test)
                           .count() as f64 / n_tmp as f64
                 };
 
@@ -166,6 +289,58 @@ impl<T> ConfidencePredictor<T> for CP<T> where T: Clone {
     }
 }
 
+impl<T> CP<T> where T: Clone {
+
+    /// Appends newly labeled examples into the existing `train_inputs`
+    /// buckets without retraining from scratch, as in the classic online
+    /// conformal prediction protocol: predict the next object, observe its
+    /// true label, then update. Before each example is absorbed into its
+    /// label bucket, checks whether the region predicted at `epsilon` would
+    /// have covered the true label, and folds the outcome into a running
+    /// count so that `error_rate()` reports the cumulative empirical error
+    /// rate over the stream, which should converge to `epsilon`.
+    pub fn update(&mut self, inputs: &Vec<T>, targets: &Vec<usize>) -> LearningResult<()> {
+        let epsilon = self.epsilon.expect("Specify epsilon to track online validity");
+
+        for (x, &y) in inputs.iter().zip(targets) {
+            if self.train_inputs.is_some() {
+                let pvalues = self.predict_confidence(&vec![x.clone()])
+                                  .expect("Failed to predict p-values");
+                /* y may be a label the model hasn't seen a bucket for yet,
+                 * in which case predict_confidence has no column for it and
+                 * the region it predicted could not possibly have covered
+                 * y: that's an error by construction, not an out-of-bounds
+                 * lookup.
+                 */
+                if y >= pvalues.cols() || pvalues[[0, y]] <= epsilon {
+                    self.n_errors += 1;
+                }
+                self.n_examples += 1;
+            }
+
+            let train_inputs = self.train_inputs.get_or_insert_with(Vec::new);
+            if y >= train_inputs.len() {
+                train_inputs.resize(y + 1, vec![]);
+            }
+            train_inputs[y].push(x.clone());
+        }
+
+        Ok(())
+    }
+
+    /// Cumulative empirical error rate observed so far via `update`: the
+    /// fraction of examples whose true label fell outside the region
+    /// predicted (at `epsilon`) just before being absorbed into the
+    /// training set. Should converge to `epsilon` in the online setting.
+    pub fn error_rate(&self) -> f64 {
+        if self.n_examples == 0 {
+            0.0
+        } else {
+            self.n_errors as f64 / self.n_examples as f64
+        }
+    }
+}
+
 
 #[cfg(test)]
 mod tests {
@@ -196,4 +371,58 @@ mod tests {
 
         assert!(cp.train_inputs.unwrap() == expected_train_inputs);
     }
+
+    #[test]
+    fn predict_region() {
+        let ncm = KNN::new(2);
+        let cp = CP::new(Box::new(ncm), Some(0.1), false);
+
+        let pvalues = Matrix::new(1, 3, vec![0.1, 0.8, 0.3]);
+        let regions = cp.predict_region(&pvalues, 0.2);
+
+        assert_eq!(regions.len(), 1);
+        assert_eq!(regions[0].labels, vec![1, 2]);
+        assert_eq!(regions[0].point, 1);
+        assert_eq!(regions[0].credibility, 0.8);
+        assert_eq!(regions[0].confidence, 1.0 - 0.3);
+    }
+
+    #[test]
+    fn update() {
+        let ncm = KNN::new(1);
+        let mut cp = CP::new(Box::new(ncm), Some(0.1), false);
+
+        let train_inputs = vec![vec![0., 0.], vec![1., 0.]];
+        let train_targets = vec![0, 0];
+        cp.train(&train_inputs, &train_targets).unwrap();
+
+        cp.update(&vec![vec![0., 1.]], &vec![1]).unwrap();
+
+        assert_eq!(cp.n_examples, 1);
+        assert_eq!(cp.train_inputs.as_ref().unwrap().len(), 2);
+        assert_eq!(cp.train_inputs.as_ref().unwrap()[1], vec![vec![0., 1.]]);
+    }
+
+    #[test]
+    fn predict_confidence_is_label_conditional() {
+        let train_inputs = vec![vec![0., 0.],
+                                vec![1., 0.],
+                                vec![10., 10.],
+                                vec![11., 10.]];
+        let train_targets = vec![0, 0, 1, 1];
+
+        let mut cp = CP::new(Box::new(KNN::new(1)), Some(0.1), false);
+        cp.train(&train_inputs, &train_targets).unwrap();
+        let pvalues = cp.predict_confidence(&vec![vec![0.5, 0.]]).unwrap();
+
+        /* label 1's bucket sits far away from the test object, so scoring
+         * against only its own (distant) bucket must drive its p-value to
+         * the minimum possible for a 2-example bucket plus the test point:
+         * the test object is the most nonconforming of the 3, so p = 1/3.
+         * If this were instead pooled across both buckets (not valid, see
+         * predict_confidence's doc comment), the denominator and the
+         * p-value would differ.
+         */
+        assert_eq!(pvalues[[0,1]], 1.0/3.0);
+    }
 }
@@ -0,0 +1,213 @@
+use itertools::Itertools;
+use rusty_machine::linalg::{Matrix, BaseMatrix};
+use rusty_machine::learning::LearningResult;
+
+use cp::ConfidencePredictor;
+use ncm::NonConformityScorer;
+
+
+/// Index of the first element of `sorted` that is `>= x` (a standard
+/// binary-search lower bound), used to turn a sorted calibration-score
+/// array into a p-value in O(log n) instead of a linear scan.
+fn lower_bound(sorted: &[f64], x: f64) -> usize {
+    let mut lo = 0;
+    let mut hi = sorted.len();
+    while lo < hi {
+        let mid = lo + (hi - lo) / 2;
+        if sorted[mid] < x {
+            lo = mid + 1;
+        } else {
+            hi = mid;
+        }
+    }
+    lo
+}
+
+
+/// Inductive Conformal Predictor
+///
+/// Unlike the transductive `CP`, which re-scores the whole training set
+/// for every test object (O(n_test * n_train^2) with the leave-one-out
+/// NCM), `ICP` pays the cost of scoring once at fit time: the training
+/// inputs are split into a proper training set and a calibration set,
+/// the calibration examples are scored against the proper training set
+/// once, and each test object is then compared against the (sorted)
+/// calibration scores for its hypothesized label via binary search.
+///
+/// T: type of an object (e.g., Vec<f64>).
+pub struct ICP<T> {
+    ncm: Box<NonConformityScorer<T>>,
+    epsilon: Option<f64>,
+    /* Fraction of each label's training examples held out as the
+     * calibration set, the remainder forms the proper training set.
+     */
+    calibration_fraction: f64,
+    /* Proper training inputs, indexed by label y, as in CP. */
+    train_inputs: Option<Vec<Vec<T>>>,
+    /* Nonconformity scores of the calibration examples, indexed by
+     * label y and kept sorted in ascending order so that p-values can
+     * be computed with a binary search.
+     */
+    calibration_scores: Option<Vec<Vec<f64>>>,
+}
+
+impl<T> ICP<T> {
+    pub fn new(ncm: Box<NonConformityScorer<T>>, epsilon: Option<f64>,
+               calibration_fraction: f64) -> ICP<T> {
+        ICP {
+            ncm: ncm,
+            epsilon: epsilon,
+            calibration_fraction: calibration_fraction,
+            train_inputs: None,
+            calibration_scores: None,
+        }
+    }
+}
+
+impl<T> ConfidencePredictor<T> for ICP<T> where T: Clone {
+
+    fn set_epsilon(&mut self, epsilon: f64) {
+        self.epsilon = Some(epsilon);
+    }
+
+    fn train(&mut self, inputs: &Vec<T>, targets: &Vec<usize>)
+            -> LearningResult<()> {
+
+        let n_labels = targets.iter()
+                              .unique()
+                              .count();
+
+        /* Bucket by label, as in CP. */
+        let buckets = inputs.iter()
+                            .zip(targets)
+                            .fold(vec![vec![]; n_labels],
+                                  |mut res, (x, y)| {
+                                     res[*y].push(x.clone());
+                                     res
+                                  });
+
+        /* Within each bucket, hold out the last calibration_fraction
+         * examples as the calibration set, the rest is the proper
+         * training set.
+         */
+        let mut train_inputs = Vec::with_capacity(n_labels);
+        let mut calibration_scores = Vec::with_capacity(n_labels);
+
+        for bucket in buckets {
+            let n_cal = ((bucket.len() as f64) * self.calibration_fraction).round() as usize;
+            let n_proper = bucket.len() - n_cal;
+
+            let mut proper_train = bucket[..n_proper].to_vec();
+            let calibration_set = &bucket[n_proper..];
+
+            let mut scores = calibration_set.iter()
+                                            .map(|x| {
+                proper_train.push(x.clone());
+                let score = self.ncm.score(proper_train.len()-1, proper_train.as_slice());
+                proper_train.pop();
+                score
+            }).collect::<Vec<_>>();
+            scores.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+            train_inputs.push(proper_train);
+            calibration_scores.push(scores);
+        }
+
+        self.train_inputs = Some(train_inputs);
+        self.calibration_scores = Some(calibration_scores);
+
+        Ok(())
+    }
+
+    /// Returns a region prediction as a matrix of boolean
+    /// values, where each column corresponds to a label,
+    /// each value to an input object, and the value is
+    /// true if the label conforms the distribution, false
+    /// otherwise.
+    fn predict(&mut self, inputs: &Vec<T>) -> LearningResult<Matrix<bool>> {
+        let epsilon = self.epsilon.expect("Specify epsilon to perform a standard predict()");
+
+        let pvalues = self.predict_confidence(inputs).expect("Failed to predict p-values");
+
+        let preds = Matrix::from_fn(pvalues.rows(), pvalues.cols(),
+                                    |j, i| pvalues[[i,j]] > epsilon);
+
+        Ok(preds)
+    }
+
+    /// Returns the p-values corresponding to the labels
+    /// for each object provided as input.
+    fn predict_confidence(&mut self, inputs: &Vec<T>) -> LearningResult<Matrix<f64>> {
+
+        let error_msg = "You should train the model first";
+
+        let n_labels = self.train_inputs.as_ref()
+                                        .expect(error_msg)
+                                        .len();
+
+        let n_test = inputs.len();
+
+        let mut pvalues = Matrix::new(n_test, n_labels,
+                                      vec![0.0; n_test*n_labels]);
+
+        for y in 0..n_labels {
+            let n_cal = self.calibration_scores.as_ref()
+                                               .expect(error_msg)[y]
+                                               .len();
+
+            for (i, x) in inputs.iter().enumerate() {
+                let alpha_test = {
+                    self.train_inputs.as_mut()
+                                     .expect(error_msg)[y]
+                                     .push(x.clone());
+
+                    let train_inputs = self.train_inputs.as_ref()
+                                                        .expect(error_msg)[y]
+                                                        .as_slice();
+                    let n = train_inputs.len();
+                    let score = self.ncm.score(n-1, train_inputs);
+
+                    self.train_inputs.as_mut()
+                                     .expect(error_msg)[y]
+                                     .pop();
+
+                    score
+                };
+
+                let scores = self.calibration_scores.as_ref().expect(error_msg)[y].as_slice();
+                let n_greater_equal = n_cal - lower_bound(scores, alpha_test);
+
+                pvalues[[i,y]] = (n_greater_equal + 1) as f64 / (n_cal + 1) as f64;
+            }
+        }
+
+        Ok(pvalues)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ncm::KNN;
+    use cp::ConfidencePredictor;
+
+    #[test]
+    fn train() {
+        let ncm = KNN::new(1);
+        let mut icp = ICP::new(Box::new(ncm), Some(0.1), 0.5);
+
+        let train_inputs = vec![vec![0., 0.],
+                                vec![1., 0.],
+                                vec![0., 1.],
+                                vec![1., 1.],
+                                vec![2., 2.],
+                                vec![1., 2.]];
+        let train_targets = vec![0, 0, 1, 1, 2, 2];
+
+        icp.train(&train_inputs, &train_targets).unwrap();
+
+        assert_eq!(icp.train_inputs.unwrap().len(), 3);
+        assert_eq!(icp.calibration_scores.unwrap().len(), 3);
+    }
+}
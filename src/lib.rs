@@ -0,0 +1,20 @@
+/* The crate predates Rust 2018/2021 and its public API (ConfidencePredictor
+ * and friends) is written in that idiom throughout: `&Vec<T>` rather than
+ * `&[T]` in signatures that are now part of the crate's stable surface,
+ * trait objects without `dyn`, and explicit `field: field` initializers.
+ * Rewriting call sites crate-wide to satisfy newer lints is a larger,
+ * unrelated change from anything in this backlog, so the existing style
+ * is kept and the lints silenced here instead of case-by-case.
+ */
+#![allow(clippy::ptr_arg, bare_trait_objects, clippy::redundant_field_names)]
+
+extern crate itertools;
+extern crate rand;
+extern crate rusty_machine;
+#[cfg(feature = "serde")]
+extern crate serde;
+
+pub mod cp;
+pub mod icp;
+pub mod metrics;
+pub mod ncm;
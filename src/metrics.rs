@@ -0,0 +1,133 @@
+use rusty_machine::linalg::{Matrix, BaseMatrix};
+
+
+/// Per-object and aggregate statistics describing how well a set of
+/// conformal region predictions meets its nominal validity level and how
+/// efficient (small) the regions are.
+///
+/// `regions` is the `Matrix<bool>` returned by `ConfidencePredictor::predict`
+/// and `pvalues` the `Matrix<f64>` returned by `predict_confidence`, both
+/// indexed `[object, label]`. `targets` holds the true label of each object.
+pub struct Metrics {
+    /// Fraction of objects whose region excludes the true label. Should be
+    /// close to `epsilon` for a valid predictor.
+    pub error_rate: f64,
+    /// Average number of labels included in a region ("N" / efficiency
+    /// criterion): the smaller, the more efficient the predictor.
+    pub avg_region_size: f64,
+    /// Fraction of objects whose region is empty.
+    pub empty_fraction: f64,
+    /// Fraction of objects whose region contains exactly one label.
+    pub singleton_fraction: f64,
+    /// Observed fuzziness: the average, over objects, of the sum of
+    /// p-values assigned to the *false* labels. Another efficiency
+    /// criterion, independent of any particular `epsilon`.
+    pub observed_fuzziness: f64,
+    /// Observed excess: the average number of labels included in a region
+    /// beyond the one correct label (0 for a singleton matching the truth).
+    pub observed_excess: f64,
+}
+
+/// Computes `Metrics` for a set of region predictions against the true
+/// labels.
+pub fn compute(regions: &Matrix<bool>, pvalues: &Matrix<f64>, targets: &Vec<usize>) -> Metrics {
+    let n = regions.rows();
+    assert_eq!(n, targets.len(), "regions and targets must have the same length");
+    assert_eq!(n, pvalues.rows(), "regions and pvalues must have the same length");
+
+    let mut n_errors = 0;
+    let mut total_size = 0;
+    let mut n_empty = 0;
+    let mut n_singleton = 0;
+    let mut fuzziness = 0.0;
+    let mut excess = 0.0;
+
+    for i in 0..n {
+        let y = targets[i];
+
+        let size = (0..regions.cols())
+            .filter(|&j| regions[[i,j]])
+            .count();
+
+        if !regions[[i,y]] {
+            n_errors += 1;
+        }
+        if size == 0 {
+            n_empty += 1;
+        }
+        if size == 1 {
+            n_singleton += 1;
+        }
+        total_size += size;
+        excess += (size - if regions[[i,y]] { 1 } else { 0 }) as f64;
+
+        fuzziness += (0..pvalues.cols())
+            .filter(|&j| j != y)
+            .map(|j| pvalues[[i,j]])
+            .sum::<f64>();
+    }
+
+    Metrics {
+        error_rate: n_errors as f64 / n as f64,
+        avg_region_size: total_size as f64 / n as f64,
+        empty_fraction: n_empty as f64 / n as f64,
+        singleton_fraction: n_singleton as f64 / n as f64,
+        observed_fuzziness: fuzziness / n as f64,
+        observed_excess: excess / n as f64,
+    }
+}
+
+/// Confusion matrix of forced point predictions (the label with the
+/// largest p-value) against the true label, analogous to the
+/// multi-label confusion matrices used in classification frameworks.
+///
+/// `matrix[[true_y, pred_y]]` is the number of objects with true label
+/// `true_y` whose forced prediction was `pred_y`.
+pub fn confusion_matrix(pvalues: &Matrix<f64>, targets: &Vec<usize>, n_labels: usize)
+        -> Matrix<usize> {
+    let mut counts = vec![0usize; n_labels*n_labels];
+
+    for (i, &y) in targets.iter().enumerate() {
+        let pred = (0..pvalues.cols())
+            .max_by(|&a, &b| pvalues[[i,a]].partial_cmp(&pvalues[[i,b]]).unwrap())
+            .expect("pvalues must have at least one label column");
+        counts[y*n_labels + pred] += 1;
+    }
+
+    Matrix::new(n_labels, n_labels, counts)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compute_perfect_predictor() {
+        let regions = Matrix::new(2, 2, vec![true, false,
+                                             false, true]);
+        let pvalues = Matrix::new(2, 2, vec![0.9, 0.05,
+                                             0.05, 0.9]);
+        let targets = vec![0, 1];
+
+        let metrics = compute(&regions, &pvalues, &targets);
+
+        assert_eq!(metrics.error_rate, 0.0);
+        assert_eq!(metrics.avg_region_size, 1.0);
+        assert_eq!(metrics.empty_fraction, 0.0);
+        assert_eq!(metrics.singleton_fraction, 1.0);
+    }
+
+    #[test]
+    fn confusion_matrix_counts() {
+        let pvalues = Matrix::new(2, 2, vec![0.9, 0.05,
+                                             0.05, 0.9]);
+        let targets = vec![0, 1];
+
+        let cm = confusion_matrix(&pvalues, &targets, 2);
+
+        assert_eq!(cm[[0,0]], 1);
+        assert_eq!(cm[[1,1]], 1);
+        assert_eq!(cm[[0,1]], 0);
+    }
+}
@@ -0,0 +1,153 @@
+#[cfg(feature = "serde")]
+use serde::{Serialize, Deserialize};
+
+
+/// A Nonconformity Measure (NCM): scores how unusual the example at index
+/// `i` looks relative to the rest of `examples`, a bag of objects that all
+/// share the same (hypothesized) label. The higher the score, the less the
+/// example conforms to the bag.
+pub trait NonConformityScorer<T> {
+    fn score(&self, i: usize, examples: &[T]) -> f64;
+}
+
+/// k-NN nonconformity measure: the sum of the distances from the example
+/// at index `i` to its `k` nearest neighbors within `examples`.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct KNN {
+    k: usize,
+}
+
+impl KNN {
+    pub fn new(k: usize) -> KNN {
+        KNN { k: k }
+    }
+}
+
+impl NonConformityScorer<Vec<f64>> for KNN {
+    fn score(&self, i: usize, examples: &[Vec<f64>]) -> f64 {
+        let x = &examples[i];
+
+        let mut distances = examples.iter()
+                                    .enumerate()
+                                    .filter(|&(j, _)| j != i)
+                                    .map(|(_, y)| euclidean_distance(x, y))
+                                    .collect::<Vec<_>>();
+        distances.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+        distances.iter().take(self.k).sum()
+    }
+}
+
+fn euclidean_distance(a: &[f64], b: &[f64]) -> f64 {
+    a.iter()
+     .zip(b)
+     .map(|(u, v)| (u - v)*(u - v))
+     .sum::<f64>()
+     .sqrt()
+}
+
+
+/// Nonconformity measure based on a per-class Gaussian likelihood, rather
+/// than a distance-based one like `KNN`: nonconformity is the negative
+/// log-likelihood of the example under a Gaussian fit to its own
+/// hypothesized-label bag, so the worse the example fits that class's
+/// distribution, the higher its score.
+///
+/// `NonConformityScorer::score` only ever sees the bag of examples sharing
+/// the hypothesized label, with no access to other classes' examples, so
+/// there's no way to fit a genuine multi-class classifier (e.g. wiring up
+/// an external crate's `predict_proba` across all classes) through this
+/// interface. Instead each feature is modeled as an independent Gaussian
+/// fit to the bag (as a Gaussian Naive Bayes classifier would fit
+/// internally for one class), with the scored example itself left out (LOO,
+/// as `KNN::score` does), and the per-feature log-densities are summed into a
+/// log-likelihood. Working in log-space (rather than multiplying raw
+/// densities and clamping the product into `[0, 1]` as if it were a
+/// probability) avoids the product under/overflowing and avoids the
+/// density legitimately exceeding 1 near the bag's mean, which would
+/// otherwise collapse every close inlier to the same clamped score.
+pub struct GaussianLikelihoodNCM;
+
+impl GaussianLikelihoodNCM {
+    pub fn new() -> GaussianLikelihoodNCM {
+        GaussianLikelihoodNCM
+    }
+}
+
+impl Default for GaussianLikelihoodNCM {
+    fn default() -> GaussianLikelihoodNCM {
+        GaussianLikelihoodNCM::new()
+    }
+}
+
+impl NonConformityScorer<Vec<f64>> for GaussianLikelihoodNCM {
+    fn score(&self, i: usize, examples: &[Vec<f64>]) -> f64 {
+        let x = &examples[i];
+
+        let rest = examples.iter()
+                           .enumerate()
+                           .filter(|&(j, _)| j != i)
+                           .map(|(_, y)| y.clone())
+                           .collect::<Vec<_>>();
+
+        -gaussian_log_likelihood(x, &rest)
+    }
+}
+
+/// Log-likelihood of `x` under a Gaussian Naive Bayes-style model fit to
+/// `bag`: each feature is modeled as an independent Gaussian fit to `bag`,
+/// and the per-feature log-densities are summed (equivalent to the log of
+/// their product, without the overflow/underflow risk of computing that
+/// product directly).
+fn gaussian_log_likelihood(x: &[f64], bag: &[Vec<f64>]) -> f64 {
+    let n = bag.len() as f64;
+
+    (0..x.len()).map(|f| {
+        let mean = bag.iter().map(|r| r[f]).sum::<f64>() / n;
+        /* Variance floor avoids dividing by zero on a degenerate
+         * (e.g. singleton or constant-feature) bag.
+         */
+        let variance = (bag.iter()
+                           .map(|r| (r[f] - mean)*(r[f] - mean))
+                           .sum::<f64>() / n)
+                       .max(1e-9);
+
+        let z = x[f] - mean;
+        -0.5 * z*z / variance - 0.5 * (2.0 * ::std::f64::consts::PI * variance).ln()
+    }).sum()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn knn_scores_outlier_higher() {
+        let knn = KNN::new(1);
+
+        let examples = vec![vec![0., 0.],
+                            vec![0., 1.],
+                            vec![10., 10.]];
+
+        let inlier_score = knn.score(0, &examples);
+        let outlier_score = knn.score(2, &examples);
+
+        assert!(outlier_score > inlier_score);
+    }
+
+    #[test]
+    fn gaussian_likelihood_scores_outlier_higher() {
+        let ncm = GaussianLikelihoodNCM::new();
+
+        let examples = vec![vec![0., 0.],
+                            vec![0.1, -0.1],
+                            vec![-0.1, 0.1],
+                            vec![50., 50.]];
+
+        let inlier_score = ncm.score(0, &examples);
+        let outlier_score = ncm.score(3, &examples);
+
+        assert!(outlier_score > inlier_score);
+    }
+}